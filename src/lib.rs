@@ -2,7 +2,8 @@ use std::{
     fs::{File, OpenOptions},
     io,
     ops::{Deref, DerefMut},
-    path::Path,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use thiserror::Error;
@@ -13,6 +14,11 @@ mod windows;
 #[cfg(unix)]
 mod unix;
 
+#[cfg(all(feature = "tokio", unix))]
+use unix::lock_region_raw;
+#[cfg(all(feature = "tokio", windows))]
+use windows::lock_region_raw;
+
 /// An enumeration of possible errors which can occur while trying to acquire a lock.
 #[derive(Debug, Error)]
 pub enum FileLockError {
@@ -22,6 +28,9 @@ pub enum FileLockError {
     /// The error occurred during I/O operations.
     #[error("I/O error: {0}")]
     IOError(#[from] io::Error),
+    /// The lock could not be acquired before the requested timeout elapsed.
+    #[error("timed out waiting to acquire the lock")]
+    TimedOut,
 }
 
 /// An enumeration of types which represents how to acquire an advisory lock.
@@ -33,6 +42,83 @@ pub enum FileLockMode {
     Shared,
 }
 
+/// A lock on a byte range of a [`File`], released automatically on drop.
+///
+/// Returned by [`AdvisoryFileLock::lock_range`] and [`AdvisoryFileLock::try_lock_range`].
+/// Unlike [`AdvisoryFileLock`] itself, a `FileLockGuard` does not own the underlying
+/// file; it only borrows it for the duration of the lock, so several guards for
+/// disjoint (or, with `Shared` mode, overlapping) ranges of the same file can coexist.
+pub struct FileLockGuard<'a> {
+    file: &'a File,
+    offset: u64,
+    len: u64,
+}
+
+impl<'a> FileLockGuard<'a> {
+    fn new(
+        file: &'a File,
+        mode: FileLockMode,
+        offset: u64,
+        len: u64,
+        blocking: bool,
+    ) -> Result<Self, FileLockError> {
+        Self::lock_region_impl(file, mode, offset, len, blocking)?;
+        Ok(FileLockGuard { file, offset, len })
+    }
+}
+
+impl Drop for FileLockGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = Self::unlock_region_impl(self.file, self.offset, self.len) {
+            log::error!(
+                "[FileLockGuard] unlock_region failed during dropping: {}",
+                err
+            );
+        }
+    }
+}
+
+impl Deref for FileLockGuard<'_> {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target {
+        self.file
+    }
+}
+
+/// Extension trait to advisory-lock a [`File`] you already own, in place.
+///
+/// [`AdvisoryFileLock::new`] re-opens the path with its own opinionated [`OpenOptions`]
+/// (read always, write and create only for [`FileLockMode::Exclusive`]), which forces
+/// locking to go through construction from a path. `AdvisoryFileLockExt` instead locks a
+/// `File` directly, so it works with files opened with arbitrary flags, already-open
+/// file descriptors, and anything else a caller can hand a `File` for.
+pub trait AdvisoryFileLockExt {
+    /// Acquire an advisory lock on the whole file. Blocks until it succeeds or errors.
+    ///
+    /// Named `lock_file` rather than `lock` so it isn't shadowed by `std::fs::File`'s own
+    /// inherent `lock` method.
+    fn lock_file(&self, mode: FileLockMode) -> Result<(), FileLockError>;
+    /// Try to acquire an advisory lock on the whole file, returning immediately.
+    fn try_lock_file(&self, mode: FileLockMode) -> Result<(), FileLockError>;
+    /// Unlock the whole file.
+    fn unlock_file(&self) -> Result<(), FileLockError>;
+}
+
+impl AdvisoryFileLockExt for File {
+    fn lock_file(&self, mode: FileLockMode) -> Result<(), FileLockError> {
+        FileLockGuard::lock_region_impl(self, mode, 0, 0, true)
+    }
+
+    fn try_lock_file(&self, mode: FileLockMode) -> Result<(), FileLockError> {
+        FileLockGuard::lock_region_impl(self, mode, 0, 0, false)
+    }
+
+    fn unlock_file(&self) -> Result<(), FileLockError> {
+        FileLockGuard::unlock_region_impl(self, 0, 0)
+    }
+}
+
 /// An advisory lock for files.
 ///
 /// An advisory lock provides a mutual-exclusion mechanism among processes which explicitly
@@ -43,10 +129,20 @@ pub enum FileLockMode {
 /// - Shared or exclusive modes.
 /// - All operations are thread-safe.
 ///
+/// ## Lock guards
+///
+/// [`AdvisoryFileLock::lock`] and [`AdvisoryFileLock::try_lock`] track whether the lock is
+/// held in the `locked` field and release it when `self` is dropped, which makes it easy to
+/// forget to call [`AdvisoryFileLock::unlock`] and leaves the lock state invisible at the type
+/// level. [`AdvisoryFileLock::lock_guard`] and [`AdvisoryFileLock::try_lock_guard`] are the
+/// recommended alternative: they return a [`FileLockGuard`] that is only held while it's in
+/// scope and unlocks on drop, the same way [`AdvisoryFileLock::lock_range`] already does.
+///
 /// ## Notes
 ///
 /// `AdvisoryFileLock` has following limitations:
-/// - Locks are allowed only on files, but not directories.
+/// - Locks are allowed only on files, but not directories. Use [`lock_dir`] to coordinate
+///   exclusive access to a directory instead.
 pub struct AdvisoryFileLock {
     /// An underlying file.
     file: File,
@@ -57,6 +153,11 @@ pub struct AdvisoryFileLock {
 
 impl AdvisoryFileLock {
     /// Create a new `FileLock`.
+    ///
+    /// The file is always opened read-write (only created if it doesn't exist when
+    /// `file_lock_mode` is [`FileLockMode::Exclusive`]), even for a [`FileLockMode::Shared`]
+    /// lock, so that an exclusive [`AdvisoryFileLock::lock_range`] also works on it: on Unix
+    /// that is an `fcntl` write lock, which needs a descriptor opened for writing.
     pub fn new<P: AsRef<Path>>(
         path: P,
         file_lock_mode: FileLockMode,
@@ -64,8 +165,8 @@ impl AdvisoryFileLock {
         let is_exclusive = file_lock_mode == FileLockMode::Exclusive;
         let file = OpenOptions::new()
             .read(true)
+            .write(true)
             .create(is_exclusive)
-            .write(is_exclusive)
             .open(path)?;
 
         Ok(AdvisoryFileLock {
@@ -109,6 +210,184 @@ impl AdvisoryFileLock {
         self.locked = false;
         Ok(())
     }
+
+    /// Acquire the advisory file lock, returning a guard that releases it on drop.
+    ///
+    /// This is the recommended alternative to [`AdvisoryFileLock::lock`]: it makes the lock's
+    /// lifetime lexically scoped instead of relying on remembering to call
+    /// [`AdvisoryFileLock::unlock`]. `lock_guard` is blocking; it will block the current thread
+    /// until it succeeds or errors.
+    pub fn lock_guard(&self) -> Result<FileLockGuard<'_>, FileLockError> {
+        self.lock_range(0, 0, self.file_lock_mode)
+    }
+
+    /// Try to acquire the advisory file lock, returning a guard that releases it on drop.
+    ///
+    /// Returns immediately with [`FileLockError::AlreadyLocked`] if the lock is held by
+    /// another process. See [`AdvisoryFileLock::lock_guard`] for why this is the recommended
+    /// alternative to [`AdvisoryFileLock::try_lock`].
+    pub fn try_lock_guard(&self) -> Result<FileLockGuard<'_>, FileLockError> {
+        self.try_lock_range(0, 0, self.file_lock_mode)
+    }
+
+    /// Acquire the advisory file lock without blocking the calling async task.
+    ///
+    /// Calling the blocking [`AdvisoryFileLock::lock`] (or [`AdvisoryFileLock::lock_guard`])
+    /// from inside a `tokio` task parks the worker thread on a syscall that may block
+    /// indefinitely, starving the rest of the runtime. `lock_async` instead offloads the
+    /// blocking wait to `tokio`'s blocking thread pool via
+    /// [`tokio::task::spawn_blocking`](https://docs.rs/tokio/latest/tokio/task/fn.spawn_blocking.html)
+    /// and resolves once the lock has been acquired. Requires the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub async fn lock_async(&self) -> Result<FileLockGuard<'_>, FileLockError> {
+        let handle = self.raw_handle();
+        let mode = self.file_lock_mode;
+        tokio::task::spawn_blocking(move || lock_region_raw(handle, mode, 0, 0, true))
+            .await
+            .map_err(|err| FileLockError::IOError(io::Error::other(err)))??;
+        Ok(FileLockGuard {
+            file: &self.file,
+            offset: 0,
+            len: 0,
+        })
+    }
+
+    /// Try to acquire the advisory file lock, returning immediately.
+    ///
+    /// Unlike [`AdvisoryFileLock::lock_async`], there is nothing to wait for here, so this
+    /// simply runs the existing non-blocking lock attempt and resolves right away. Requires
+    /// the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub async fn try_lock_async(&self) -> Result<FileLockGuard<'_>, FileLockError> {
+        self.try_lock_guard()
+    }
+
+    /// Acquire the advisory file lock, giving up with [`FileLockError::TimedOut`] if it
+    /// can't be acquired within `timeout`.
+    ///
+    /// This is useful for daemons and CLIs that must not hang forever waiting on a
+    /// contended lock.
+    pub fn lock_timeout(
+        &mut self,
+        mode: FileLockMode,
+        timeout: Duration,
+    ) -> Result<(), FileLockError> {
+        self.lock_poll(mode, Some(Instant::now() + timeout), || {})
+    }
+
+    /// Acquire the advisory file lock, invoking `on_wait` if the lock isn't immediately
+    /// available and we're about to block.
+    ///
+    /// This mirrors the "Blocking waiting for file lock..." message Cargo prints when it
+    /// has to wait on a contended lock, letting callers surface the same kind of
+    /// user-facing progress notice.
+    pub fn lock_with(
+        &mut self,
+        mode: FileLockMode,
+        on_wait: impl FnOnce(),
+    ) -> Result<(), FileLockError> {
+        self.lock_poll(mode, None, on_wait)
+    }
+
+    fn lock_poll(
+        &mut self,
+        mode: FileLockMode,
+        deadline: Option<Instant>,
+        on_wait: impl FnOnce(),
+    ) -> Result<(), FileLockError> {
+        match self.try_lock_mode_impl(mode) {
+            Ok(()) => {
+                self.file_lock_mode = mode;
+                self.locked = true;
+                return Ok(());
+            }
+            Err(FileLockError::AlreadyLocked) => {}
+            Err(err) => return Err(err),
+        }
+
+        on_wait();
+
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(FileLockError::TimedOut);
+            }
+            std::thread::sleep(backoff);
+            match self.try_lock_mode_impl(mode) {
+                Ok(()) => {
+                    self.file_lock_mode = mode;
+                    self.locked = true;
+                    return Ok(());
+                }
+                Err(FileLockError::AlreadyLocked) => {
+                    backoff = (backoff * 2).min(Duration::from_millis(200));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Convert an exclusive hold on this lock down to a shared hold, without closing the
+    /// underlying descriptor.
+    ///
+    /// This is **not** guaranteed to be atomic on either platform: on Unix it re-locks the
+    /// already-open descriptor with `flock(LOCK_SH)`, and per `flock(2)`, "converting a lock
+    /// is not guaranteed to be atomic: the existing lock is first removed, and then a new
+    /// lock is established. Between these two steps, a pending lock request by another
+    /// process may be granted." On Windows this unlocks and re-locks in shared mode via
+    /// separate `LockFileEx` calls, which has the same race. On Unix the re-lock blocks until
+    /// it can be granted; on Windows it does not wait, and fails with
+    /// [`FileLockError::AlreadyLocked`] if another process wins that window, in which case
+    /// the lock is no longer held.
+    pub fn downgrade(&mut self) -> Result<(), FileLockError> {
+        self.relock_impl(FileLockMode::Shared)?;
+        self.file_lock_mode = FileLockMode::Shared;
+        Ok(())
+    }
+
+    /// Convert a shared hold on this lock up to an exclusive hold, without closing the
+    /// underlying descriptor.
+    ///
+    /// See [`AdvisoryFileLock::downgrade`] for why this is not guaranteed to be atomic on
+    /// either platform.
+    pub fn upgrade(&mut self) -> Result<(), FileLockError> {
+        self.relock_impl(FileLockMode::Exclusive)?;
+        self.file_lock_mode = FileLockMode::Exclusive;
+        Ok(())
+    }
+
+    /// Acquire an advisory lock on the byte range `[offset, offset + len)` of the file.
+    ///
+    /// `len == 0` means "to the end of the file", and the range need not exist within
+    /// the file yet. This lets independent callers coordinate on disjoint regions of
+    /// the same file (e.g. pages of a shared database) instead of contending on the
+    /// whole file. `lock_range` is blocking; it will block the current thread until it
+    /// succeeds or errors. The returned [`FileLockGuard`] releases the range on drop.
+    ///
+    /// On Unix, `offset == 0 && len == 0` takes a whole-file `flock` lock, while any other
+    /// range takes an `fcntl` record lock; the two kinds of lock do not conflict with each
+    /// other.
+    pub fn lock_range(
+        &self,
+        offset: u64,
+        len: u64,
+        mode: FileLockMode,
+    ) -> Result<FileLockGuard<'_>, FileLockError> {
+        FileLockGuard::new(&self.file, mode, offset, len, true)
+    }
+
+    /// Try to acquire an advisory lock on the byte range `[offset, offset + len)` of
+    /// the file, returning immediately if it's already held by another process.
+    ///
+    /// See [`AdvisoryFileLock::lock_range`] for the meaning of `offset` and `len`.
+    pub fn try_lock_range(
+        &self,
+        offset: u64,
+        len: u64,
+        mode: FileLockMode,
+    ) -> Result<FileLockGuard<'_>, FileLockError> {
+        FileLockGuard::new(&self.file, mode, offset, len, false)
+    }
 }
 
 impl Drop for AdvisoryFileLock {
@@ -139,6 +418,78 @@ impl DerefMut for AdvisoryFileLock {
     }
 }
 
+/// Options for [`lock_dir`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct DirLockOptions<'a> {
+    /// Whether to acquire an exclusive or a shared lock on the directory.
+    pub exclusive: bool,
+    /// Whether to fail immediately with [`FileLockError::AlreadyLocked`] instead of
+    /// blocking when the directory is already locked.
+    pub non_blocking: bool,
+    /// The name of the sentinel lock file created inside the directory, e.g. `.lock`.
+    pub lock_file_name: &'a str,
+}
+
+impl Default for DirLockOptions<'static> {
+    fn default() -> Self {
+        DirLockOptions {
+            exclusive: true,
+            non_blocking: false,
+            lock_file_name: ".lock",
+        }
+    }
+}
+
+/// Acquire an advisory lock on a directory, by locking a sentinel file inside it.
+///
+/// `AdvisoryFileLock` only ever locks files, never directories, so this creates (or opens)
+/// `options.lock_file_name` inside `dir` and locks that, giving callers a portable "lock
+/// this directory" primitive without each one re-inventing the sentinel-file convention.
+pub fn lock_dir(dir: &Path, options: DirLockOptions<'_>) -> Result<DirLockGuard, FileLockError> {
+    let mode = if options.exclusive {
+        FileLockMode::Exclusive
+    } else {
+        FileLockMode::Shared
+    };
+    // Unlike `AdvisoryFileLock::new`, the sentinel file must always be created if missing,
+    // regardless of `mode`: a shared lock being the first ever access to `dir` is common,
+    // and `AdvisoryFileLock::new`'s `create` is gated on `FileLockMode::Exclusive`.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(dir.join(options.lock_file_name))?;
+    let mut lock = AdvisoryFileLock {
+        file,
+        locked: false,
+        file_lock_mode: mode,
+    };
+    if options.non_blocking {
+        lock.try_lock()?;
+    } else {
+        lock.lock()?;
+    }
+    Ok(DirLockGuard {
+        dir: dir.to_path_buf(),
+        _lock: lock,
+    })
+}
+
+/// An advisory lock on a directory, acquired via [`lock_dir`]. Releases the lock on drop.
+pub struct DirLockGuard {
+    dir: PathBuf,
+    // Held only to keep the sentinel file's lock alive; unlocked by its own `Drop`.
+    _lock: AdvisoryFileLock,
+}
+
+impl DirLockGuard {
+    /// The directory this guard locks.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,4 +550,204 @@ mod tests {
         }
         std::fs::remove_file(&test_file).unwrap();
     }
+
+    #[test]
+    fn lock_guard_releases_on_drop() {
+        let mut test_file = temp_dir();
+        test_file.push("lock_guard_releases_on_drop");
+        {
+            let f1 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            {
+                let _guard = f1.lock_guard().unwrap();
+                let f2 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+                assert!(f2.try_lock_guard().is_err());
+            }
+            let f3 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            assert!(f3.try_lock_guard().is_ok());
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn lock_dir_shared_creates_missing_sentinel() {
+        let mut test_dir = temp_dir();
+        test_dir.push("lock_dir_shared_creates_missing_sentinel");
+        std::fs::create_dir_all(&test_dir).unwrap();
+        {
+            let options = DirLockOptions {
+                exclusive: false,
+                non_blocking: true,
+                ..Default::default()
+            };
+            assert!(lock_dir(&test_dir, options).is_ok());
+        }
+        std::fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn lock_dir_excludes_other_exclusive_lockers() {
+        let mut test_dir = temp_dir();
+        test_dir.push("lock_dir_excludes_other_exclusive_lockers");
+        std::fs::create_dir_all(&test_dir).unwrap();
+        {
+            let options = DirLockOptions {
+                non_blocking: true,
+                ..Default::default()
+            };
+            let guard1 = lock_dir(&test_dir, options).unwrap();
+            assert_eq!(guard1.dir(), test_dir.as_path());
+            assert!(lock_dir(&test_dir, options).is_err());
+        }
+        assert!(lock_dir(
+            &test_dir,
+            DirLockOptions {
+                non_blocking: true,
+                ..Default::default()
+            }
+        )
+        .is_ok());
+        std::fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn ext_trait_locks_an_owned_file() {
+        let mut test_file = temp_dir();
+        test_file.push("ext_trait_locks_an_owned_file");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&test_file)
+            .unwrap();
+        {
+            file.lock_file(FileLockMode::Exclusive).unwrap();
+            let other = OpenOptions::new().read(true).open(&test_file).unwrap();
+            assert!(other.try_lock_file(FileLockMode::Exclusive).is_err());
+            file.unlock_file().unwrap();
+            assert!(other.try_lock_file(FileLockMode::Exclusive).is_ok());
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn lock_timeout_expires_on_contention() {
+        let mut test_file = temp_dir();
+        test_file.push("lock_timeout_expires_on_contention");
+        {
+            let mut f1 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            f1.lock().unwrap();
+            let mut f2 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            let result = f2.lock_timeout(FileLockMode::Exclusive, Duration::from_millis(50));
+            assert!(matches!(result, Err(FileLockError::TimedOut)));
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn lock_with_invokes_on_wait_only_when_contended() {
+        let mut test_file = temp_dir();
+        test_file.push("lock_with_invokes_on_wait_only_when_contended");
+        File::create(&test_file).unwrap();
+        {
+            let mut f1 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            let mut called = false;
+            f1.lock_with(FileLockMode::Exclusive, || called = true)
+                .unwrap();
+            assert!(!called);
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn upgrade_then_downgrade() {
+        let mut test_file = temp_dir();
+        test_file.push("upgrade_then_downgrade");
+        File::create(&test_file).unwrap();
+        {
+            let mut f1 = AdvisoryFileLock::new(&test_file, FileLockMode::Shared).unwrap();
+            f1.lock().unwrap();
+            f1.upgrade().unwrap();
+            assert!(f1.is_exclusive());
+
+            let mut f2 = AdvisoryFileLock::new(&test_file, FileLockMode::Shared).unwrap();
+            assert!(f2.try_lock().is_err());
+
+            f1.downgrade().unwrap();
+            assert!(f1.is_shared());
+            assert!(f2.try_lock().is_ok());
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn disjoint_ranges_do_not_conflict() {
+        let mut test_file = temp_dir();
+        test_file.push("disjoint_ranges");
+        {
+            let f1 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            let _guard1 = f1.try_lock_range(0, 10, FileLockMode::Exclusive).unwrap();
+            let _guard2 = f1.try_lock_range(10, 10, FileLockMode::Exclusive).unwrap();
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn overlapping_exclusive_ranges_conflict() {
+        let mut test_file = temp_dir();
+        test_file.push("overlapping_ranges");
+        {
+            // On Unix, `fcntl` record locks are scoped per `(process, inode)`, so a second
+            // descriptor in this process never conflicts with them; the conflicting attempt
+            // is made from a forked child, which does not inherit the parent's record locks.
+            // On Windows, `LockFileEx` locks are per handle, so a second `AdvisoryFileLock`
+            // in this process is enough.
+            let f1 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            let f2 = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            let _guard1 = f1.try_lock_range(0, 10, FileLockMode::Exclusive).unwrap();
+            #[cfg(unix)]
+            let conflicted = {
+                let pid = unsafe { libc::fork() };
+                if pid == 0 {
+                    let conflict = f2.try_lock_range(5, 10, FileLockMode::Exclusive).is_err();
+                    unsafe { libc::_exit(if conflict { 0 } else { 1 }) };
+                }
+                let mut status = 0;
+                pid > 0
+                    && unsafe { libc::waitpid(pid, &mut status, 0) } == pid
+                    && libc::WIFEXITED(status)
+                    && libc::WEXITSTATUS(status) == 0
+            };
+            #[cfg(windows)]
+            let conflicted = f2.try_lock_range(5, 10, FileLockMode::Exclusive).is_err();
+            assert!(conflicted);
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn lock_async_waits_for_holder_to_release() {
+        let mut test_file = temp_dir();
+        test_file.push("lock_async_waits_for_holder_to_release");
+        {
+            let mut holder = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            holder.lock().unwrap();
+
+            let waiter = AdvisoryFileLock::new(&test_file, FileLockMode::Exclusive).unwrap();
+            assert!(waiter.try_lock_async().await.is_err());
+
+            let wait_for_release = waiter.lock_async();
+            assert!(
+                tokio::time::timeout(Duration::from_millis(50), wait_for_release)
+                    .await
+                    .is_err(),
+                "lock_async resolved before the holder released its lock"
+            );
+
+            holder.unlock().unwrap();
+            waiter.lock_async().await.unwrap();
+        }
+        std::fs::remove_file(&test_file).unwrap();
+    }
 }
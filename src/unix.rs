@@ -0,0 +1,165 @@
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::{AdvisoryFileLock, FileLockError, FileLockGuard, FileLockMode};
+
+impl AdvisoryFileLock {
+    pub(crate) fn lock_impl(&self) -> Result<(), FileLockError> {
+        flock(self.file.as_raw_fd(), self.file_lock_mode, true)
+    }
+
+    pub(crate) fn try_lock_impl(&self) -> Result<(), FileLockError> {
+        flock(self.file.as_raw_fd(), self.file_lock_mode, false)
+    }
+
+    pub(crate) fn unlock_impl(&self) -> Result<(), FileLockError> {
+        funlock(self.file.as_raw_fd())
+    }
+
+    /// Re-issue the whole-file lock in `mode` on the already-locked descriptor.
+    ///
+    /// Per `flock(2)`, converting a lock this way is not atomic: the kernel first drops the
+    /// existing lock and then establishes the new one, so a pending lock request from
+    /// another process may be granted in between.
+    pub(crate) fn relock_impl(&self, mode: FileLockMode) -> Result<(), FileLockError> {
+        flock(self.file.as_raw_fd(), mode, true)
+    }
+
+    /// Try to acquire the whole-file lock in `mode`, without waiting and without
+    /// touching `self.file_lock_mode` (the caller updates that on success).
+    pub(crate) fn try_lock_mode_impl(&self, mode: FileLockMode) -> Result<(), FileLockError> {
+        flock(self.file.as_raw_fd(), mode, false)
+    }
+
+    /// The raw file descriptor, as a plain `isize` so it can be moved into a
+    /// `'static` closure (e.g. for `tokio::task::spawn_blocking`) without borrowing `self`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn raw_handle(&self) -> isize {
+        self.file.as_raw_fd() as isize
+    }
+}
+
+impl<'a> FileLockGuard<'a> {
+    /// Locks either the whole file (`offset == 0 && len == 0`) via `flock`, the same
+    /// mechanism the rest of `AdvisoryFileLock` uses, or an explicit byte range via
+    /// `fcntl`. See the doc comment on [`flock`] for why these can't be unified.
+    pub(crate) fn lock_region_impl(
+        file: &std::fs::File,
+        mode: FileLockMode,
+        offset: u64,
+        len: u64,
+        blocking: bool,
+    ) -> Result<(), FileLockError> {
+        if offset == 0 && len == 0 {
+            flock(file.as_raw_fd(), mode, blocking)
+        } else {
+            fcntl_lock(file.as_raw_fd(), l_type(mode), offset, len, blocking)
+        }
+    }
+
+    pub(crate) fn unlock_region_impl(
+        file: &std::fs::File,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), FileLockError> {
+        if offset == 0 && len == 0 {
+            funlock(file.as_raw_fd())
+        } else {
+            fcntl_lock(file.as_raw_fd(), libc::F_UNLCK as libc::c_short, offset, len, true)
+        }
+    }
+}
+
+/// Whole-file advisory lock via `flock(2)`.
+///
+/// `flock` locks are scoped to the *open file description* (effectively, the descriptor
+/// that created it and its dups), unlike `fcntl`'s `F_SETLK`/`F_SETLKW` record locks, which
+/// are scoped to `(process, inode)`: a second descriptor the same process opens on the same
+/// path does *not* conflict with an `fcntl` record lock that process already holds, which
+/// would silently break mutual exclusion for `AdvisoryFileLock::new` (each instance opens
+/// its own descriptor). `flock` doesn't have that problem, so it backs every whole-file
+/// operation here; byte-range locking (see [`fcntl_lock`] and [`FileLockGuard`]) genuinely
+/// needs `fcntl`, and deliberately uses a separate, independent kernel lock table that does
+/// not interact with `flock` locks on the same file.
+fn flock(fd: RawFd, mode: FileLockMode, blocking: bool) -> Result<(), FileLockError> {
+    let mut operation = match mode {
+        FileLockMode::Shared => libc::LOCK_SH,
+        FileLockMode::Exclusive => libc::LOCK_EX,
+    };
+    if !blocking {
+        operation |= libc::LOCK_NB;
+    }
+
+    let ret = unsafe { libc::flock(fd, operation) };
+    if ret == -1 {
+        let err = std::io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EWOULDBLOCK) => Err(FileLockError::AlreadyLocked),
+            _ => Err(FileLockError::IOError(err)),
+        };
+    }
+    Ok(())
+}
+
+fn funlock(fd: RawFd) -> Result<(), FileLockError> {
+    let ret = unsafe { libc::flock(fd, libc::LOCK_UN) };
+    if ret == -1 {
+        return Err(FileLockError::IOError(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+fn l_type(mode: FileLockMode) -> libc::c_short {
+    (match mode {
+        FileLockMode::Shared => libc::F_RDLCK,
+        FileLockMode::Exclusive => libc::F_WRLCK,
+    }) as libc::c_short
+}
+
+/// Like [`FileLockGuard::lock_region_impl`], but takes the raw descriptor as the `isize` produced by
+/// [`AdvisoryFileLock::raw_handle`], for use from a blocking-pool closure.
+#[cfg(feature = "tokio")]
+pub(crate) fn lock_region_raw(
+    handle: isize,
+    mode: FileLockMode,
+    offset: u64,
+    len: u64,
+    blocking: bool,
+) -> Result<(), FileLockError> {
+    if offset == 0 && len == 0 {
+        flock(handle as RawFd, mode, blocking)
+    } else {
+        fcntl_lock(handle as RawFd, l_type(mode), offset, len, blocking)
+    }
+}
+
+/// Byte-range advisory lock via `fcntl(2)`'s `F_SETLK`/`F_SETLKW` record locks. `l_len == 0`
+/// means "to the end of the file", matching `fcntl`'s own convention.
+fn fcntl_lock(
+    fd: RawFd,
+    l_type: libc::c_short,
+    offset: u64,
+    len: u64,
+    blocking: bool,
+) -> Result<(), FileLockError> {
+    let mut flock: libc::flock = unsafe { std::mem::zeroed() };
+    flock.l_type = l_type;
+    flock.l_whence = libc::SEEK_SET as libc::c_short;
+    flock.l_start = offset as libc::off_t;
+    flock.l_len = len as libc::off_t;
+
+    let cmd = if blocking {
+        libc::F_SETLKW
+    } else {
+        libc::F_SETLK
+    };
+
+    let ret = unsafe { libc::fcntl(fd, cmd, &flock) };
+    if ret == -1 {
+        let err = std::io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EAGAIN) => Err(FileLockError::AlreadyLocked),
+            _ => Err(FileLockError::IOError(err)),
+        };
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::os::windows::io::AsRawHandle;
+
+use winapi::shared::minwindef::DWORD;
+use winapi::um::fileapi::{LockFileEx, UnlockFileEx};
+use winapi::um::minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED};
+use winapi::um::winnt::HANDLE;
+
+use crate::{AdvisoryFileLock, FileLockError, FileLockGuard, FileLockMode};
+
+impl AdvisoryFileLock {
+    pub(crate) fn lock_impl(&self) -> Result<(), FileLockError> {
+        lock_region(self.file.as_raw_handle() as HANDLE, self.file_lock_mode, 0, 0, true)
+    }
+
+    pub(crate) fn try_lock_impl(&self) -> Result<(), FileLockError> {
+        lock_region(self.file.as_raw_handle() as HANDLE, self.file_lock_mode, 0, 0, false)
+    }
+
+    pub(crate) fn unlock_impl(&self) -> Result<(), FileLockError> {
+        unlock_region(self.file.as_raw_handle() as HANDLE, 0, 0)
+    }
+
+    /// Convert the whole-file lock to `mode` by unlocking and re-locking.
+    ///
+    /// `LockFileEx` has no atomic upgrade/downgrade primitive, so there is a brief window
+    /// where the file is unlocked; another process may grab it first, in which case this
+    /// returns [`FileLockError::AlreadyLocked`] and the lock is lost.
+    pub(crate) fn relock_impl(&self, mode: FileLockMode) -> Result<(), FileLockError> {
+        self.unlock_impl()?;
+        lock_region(self.file.as_raw_handle() as HANDLE, mode, 0, 0, false)
+    }
+
+    /// Try to acquire the whole-file lock in `mode`, without waiting and without
+    /// touching `self.file_lock_mode` (the caller updates that on success).
+    pub(crate) fn try_lock_mode_impl(&self, mode: FileLockMode) -> Result<(), FileLockError> {
+        lock_region(self.file.as_raw_handle() as HANDLE, mode, 0, 0, false)
+    }
+
+    /// The raw `HANDLE`, as a plain `isize` so it can be moved into a `'static` closure
+    /// (e.g. for `tokio::task::spawn_blocking`) without borrowing `self`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn raw_handle(&self) -> isize {
+        self.file.as_raw_handle() as isize
+    }
+}
+
+impl<'a> FileLockGuard<'a> {
+    pub(crate) fn lock_region_impl(
+        file: &std::fs::File,
+        mode: FileLockMode,
+        offset: u64,
+        len: u64,
+        blocking: bool,
+    ) -> Result<(), FileLockError> {
+        lock_region(file.as_raw_handle() as HANDLE, mode, offset, len, blocking)
+    }
+
+    pub(crate) fn unlock_region_impl(
+        file: &std::fs::File,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), FileLockError> {
+        unlock_region(file.as_raw_handle() as HANDLE, offset, len)
+    }
+}
+
+/// Splits a 64-bit `offset` into the high/low `DWORD` halves that `LockFileEx`/`UnlockFileEx`
+/// expect.
+fn split(value: u64) -> (DWORD, DWORD) {
+    ((value >> 32) as DWORD, (value & 0xFFFF_FFFF) as DWORD)
+}
+
+/// Splits a 64-bit lock length into the high/low `DWORD` halves that `LockFileEx`/
+/// `UnlockFileEx` expect, with `len == 0` meaning "to the end of the file" (matching the
+/// `fcntl`/`l_len` convention used on Unix). Unlike `offset`, `LockFileEx` has no sentinel
+/// for "to EOF", so a literal `0, 0` would lock a zero-byte region that always trivially
+/// succeeds and locks nothing; map it to the maximum lockable length instead.
+fn split_len(len: u64) -> (DWORD, DWORD) {
+    if len == 0 {
+        (DWORD::MAX, DWORD::MAX)
+    } else {
+        split(len)
+    }
+}
+
+fn overlapped(offset: u64) -> OVERLAPPED {
+    let (offset_high, offset_low) = split(offset);
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    overlapped.Offset = offset_low;
+    overlapped.OffsetHigh = offset_high;
+    overlapped
+}
+
+fn lock_region(
+    handle: HANDLE,
+    mode: FileLockMode,
+    offset: u64,
+    len: u64,
+    blocking: bool,
+) -> Result<(), FileLockError> {
+    let mut flags = match mode {
+        FileLockMode::Shared => 0,
+        FileLockMode::Exclusive => LOCKFILE_EXCLUSIVE_LOCK,
+    };
+    if !blocking {
+        flags |= LOCKFILE_FAIL_IMMEDIATELY;
+    }
+    let (len_high, len_low) = split_len(len);
+    let mut overlapped = overlapped(offset);
+
+    let ret = unsafe { LockFileEx(handle, flags, 0, len_low, len_high, &mut overlapped) };
+    if ret == 0 {
+        let err = std::io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(code) if code as u32 == winapi::shared::winerror::ERROR_LOCK_VIOLATION => {
+                Err(FileLockError::AlreadyLocked)
+            }
+            _ => Err(FileLockError::IOError(err)),
+        };
+    }
+    Ok(())
+}
+
+fn unlock_region(handle: HANDLE, offset: u64, len: u64) -> Result<(), FileLockError> {
+    let (len_high, len_low) = split_len(len);
+    let mut overlapped = overlapped(offset);
+
+    let ret = unsafe { UnlockFileEx(handle, 0, len_low, len_high, &mut overlapped) };
+    if ret == 0 {
+        return Err(FileLockError::IOError(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Like [`lock_region`], but takes the raw handle as the `isize` produced by
+/// [`AdvisoryFileLock::raw_handle`], for use from a blocking-pool closure.
+#[cfg(feature = "tokio")]
+pub(crate) fn lock_region_raw(
+    handle: isize,
+    mode: FileLockMode,
+    offset: u64,
+    len: u64,
+    blocking: bool,
+) -> Result<(), FileLockError> {
+    lock_region(handle as HANDLE, mode, offset, len, blocking)
+}